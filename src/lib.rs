@@ -6,17 +6,23 @@
 
 //! command-unquoted provides [a wrapper struct][Unquoted] for
 //! [`std::process::Command`] that provides a nicer-looking [`Debug`]
-//! implementation and is useful for user-facing error messages.
+//! implementation and is useful for user-facing error messages. The
+//! [`Quotable`] extension trait exposes the same minimal-quoting rules for
+//! individual strings, for callers who want to quote a path or argument
+//! outside the context of a whole `Command`.
 //!
 //! Instead of quoting all strings (as done in the Unix `Command`
 //! implementation), quotes are added only where necessary.
 //!
 //! As with `Command`'s `Debug` implementation, this format only approximates an
 //! appropriate shell invocation of the program with the provided environment.
-//! It may be particularly unsuitable for Windows (patches welcome). Non-UTF-8
-//! data is lossily converted using the UTF-8 replacement character. This format
-//! **is not stable** and may change between releases; only the API of this
-//! crate is stable.
+//! On Windows, [`QuotingStyle::Windows`] (used automatically by [`Debug`] on
+//! that platform) instead reproduces the quoting rules
+//! `std::process::Command` itself uses to build the process's command line.
+//! On Unix, non-UTF-8 data is rendered byte-faithfully using bash's ANSI-C
+//! `$'...'` quoting; elsewhere it is lossily converted using the UTF-8
+//! replacement character. This format **is not stable** and may change
+//! between releases; only the API of this crate is stable.
 //!
 //! To keep the resulting output friendlier (and sometimes due to Rust standard
 //! library limitations), the result of these methods are not displayed in this
@@ -25,11 +31,17 @@
 //! - [`Command::env_clear`] and [`Command::env_remove`]
 //! - [`Command::stdin`], [`Command::stdout`], and [`Command::stderr`]
 //! - all methods of all `CommandExt` traits
+//!
+//! Raw, pre-quoted arguments added with a `CommandExt::raw_arg` method are
+//! indistinguishable from regular ones once they're part of a `Command`, so
+//! [`Unquoted`] has no way to avoid over-quoting them; use [`RawUnquoted`]
+//! instead to render a command built from raw arguments.
 
 #![warn(clippy::pedantic)]
 
-use std::ffi::OsStr;
+use std::ffi::{OsStr, OsString};
 use std::fmt::{self, Debug, Display};
+use std::path::Path;
 use std::process::Command;
 
 const RESERVED_COMMAND_WORDS: &[&str] = &[
@@ -39,102 +51,480 @@ const RESERVED_COMMAND_WORDS: &[&str] = &[
     "until", "while", // POSIX-1.2018
 ];
 
+/// Controls how aggressively a command's program, arguments, and
+/// environment variables are quoted.
+///
+/// Used with [`Unquoted::with_style`]; mirrors coreutils `ls`'s
+/// `--quoting-style` options.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum QuotingStyle {
+    /// Print everything verbatim, with no quoting at all. Useful when the
+    /// caller already knows the output is for display only and won't be
+    /// pasted into a shell.
+    Literal,
+    /// Quote only where a POSIX shell would require it. This is the
+    /// default, and is used by the [`Debug`] implementation on platforms
+    /// other than Windows.
+    #[default]
+    ShellMinimal,
+    /// Quote every word, even ones with no special characters, which is
+    /// handy for visually delimiting empty or whitespace-only values.
+    ShellAlways,
+    /// Always use bash's ANSI-C `$'...'` escaping.
+    ShellEscape,
+    /// Quote following the same rules `std::process::Command` uses to
+    /// build a Windows command line (and that `CommandLineToArgvW` uses to
+    /// parse one back apart). Used by the [`Debug`] implementation on
+    /// Windows.
+    Windows,
+}
+
 /// A wrapper for [`std::process::Command`] with a nicer-looking [`Debug`]
 /// implementation.
 ///
 /// See [the crate-level documentation][crate] for more details.
 pub struct Unquoted<'a>(pub &'a Command);
 
+impl<'a> Unquoted<'a> {
+    /// Renders `cmd` using an explicit [`QuotingStyle`] rather than the
+    /// platform default used by [`Debug`] ([`QuotingStyle::ShellMinimal`],
+    /// or [`QuotingStyle::Windows`] on Windows).
+    #[must_use]
+    pub fn with_style(cmd: &'a Command, style: QuotingStyle) -> Styled<'a> {
+        Styled(cmd, style)
+    }
+
+    /// Renders this command's program and arguments as a single
+    /// POSIX-shell-quoted `String`, using [`QuotingStyle::ShellMinimal`]
+    /// and omitting the `` ` `` delimiters and environment variables that
+    /// [`Debug`] includes.
+    ///
+    /// Unlike `Debug`, this is a strict round trip: splitting the result
+    /// with a POSIX shell-word tokenizer always reproduces exactly
+    /// `[program, ...args]`.
+    ///
+    /// # Panics
+    ///
+    /// Never panics; writing to a `String` is infallible.
+    #[must_use]
+    pub fn to_command_string(&self) -> String {
+        let mut s = String::new();
+        write_command_words(&mut s, self.0, QuotingStyle::ShellMinimal)
+            .expect("writing to a String cannot fail");
+        s
+    }
+}
+
 impl Debug for Unquoted<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "`")?;
-        for (name, value_opt) in self.0.get_envs() {
-            if let Some(value) = value_opt {
-                write!(f, "{}={} ", Quoted(name), Quoted(value))?;
-            }
+        #[cfg(windows)]
+        let style = QuotingStyle::Windows;
+        #[cfg(not(windows))]
+        let style = QuotingStyle::ShellMinimal;
+
+        fmt_command(f, self.0, style)
+    }
+}
+
+/// A command rendered with an explicit [`QuotingStyle`].
+///
+/// Constructed with [`Unquoted::with_style`]; see [the crate-level
+/// documentation][crate] for more details.
+pub struct Styled<'a>(&'a Command, QuotingStyle);
+
+impl Debug for Styled<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt_command(f, self.0, self.1)
+    }
+}
+
+fn fmt_command(f: &mut fmt::Formatter<'_>, cmd: &Command, style: QuotingStyle) -> fmt::Result {
+    write!(f, "`")?;
+    for (name, value_opt) in cmd.get_envs() {
+        if let Some(value) = value_opt {
+            write!(f, "{}={} ", Quoted(name, style), Quoted(value, style))?;
         }
+    }
+    write_command_words(f, cmd, style)?;
+    write!(f, "`")
+}
 
-        let program = self.0.get_program();
-        if let Some(s) = program
+/// Writes `cmd`'s program and arguments (but not its environment
+/// variables), space-separated and quoted per `style`.
+fn write_command_words(f: &mut impl fmt::Write, cmd: &Command, style: QuotingStyle) -> fmt::Result {
+    let program = cmd.get_program();
+    // Reserved words only need forced quoting in a POSIX shell, and
+    // `ShellEscape`'s `$'...'` quoting already keeps them from being
+    // recognized.
+    let reserved_word = if matches!(
+        style,
+        QuotingStyle::Literal | QuotingStyle::Windows | QuotingStyle::ShellEscape
+    ) {
+        None
+    } else {
+        program
             .to_str()
             .filter(|s| RESERVED_COMMAND_WORDS.binary_search(s).is_ok())
-        {
-            write!(f, "'{}'", s)?;
+    };
+    if let Some(s) = reserved_word {
+        write!(f, "'{s}'")?;
+    } else if style == QuotingStyle::Windows {
+        // libstd's `make_command_line` always force-quotes `argv0`.
+        fmt_windows_arg(f, &program.to_string_lossy(), true)?;
+    } else {
+        write!(f, "{}", Quoted(program, style))?;
+    }
+
+    for arg in cmd.get_args() {
+        write!(f, " {}", Quoted(arg, style))?;
+    }
+    Ok(())
+}
+
+/// Whether an argument added to [`RawUnquoted`] is quoted the usual way, or
+/// passed through verbatim the way `CommandExt::raw_arg` adds it to a
+/// `Command`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RawArg {
+    /// A regular argument, quoted the usual way.
+    Regular,
+    /// A pre-quoted argument, rendered with no escaping at all.
+    Raw,
+}
+
+/// An owned, builder-style variant of [`Unquoted`] for commands built with
+/// raw (pre-quoted, unescaped) arguments, the way `CommandExt::raw_arg`
+/// adds them.
+///
+/// A plain [`Command`] doesn't record which of its arguments were added
+/// with `raw_arg` instead of `arg`, so there's no way for [`Unquoted`] to
+/// tell them apart and avoid over-quoting the raw ones. `RawUnquoted` is
+/// built up independently of a `Command`, with each argument tagged
+/// [`RawArg::Regular`] or [`RawArg::Raw`] as it's added.
+pub struct RawUnquoted {
+    program: OsString,
+    envs: Vec<(OsString, OsString)>,
+    args: Vec<(OsString, RawArg)>,
+}
+
+impl RawUnquoted {
+    /// Creates a new `RawUnquoted` for `program`, with no environment
+    /// variables or arguments yet.
+    pub fn new(program: impl Into<OsString>) -> Self {
+        Self {
+            program: program.into(),
+            envs: Vec::new(),
+            args: Vec::new(),
+        }
+    }
+
+    /// Adds an environment variable, quoted the usual way.
+    #[must_use]
+    pub fn env(mut self, name: impl Into<OsString>, value: impl Into<OsString>) -> Self {
+        self.envs.push((name.into(), value.into()));
+        self
+    }
+
+    /// Adds a regular argument, quoted the usual way.
+    #[must_use]
+    pub fn arg(mut self, value: impl Into<OsString>) -> Self {
+        self.args.push((value.into(), RawArg::Regular));
+        self
+    }
+
+    /// Adds a raw argument, rendered verbatim with no escaping at all, the
+    /// way `CommandExt::raw_arg` adds it to a `Command`.
+    #[must_use]
+    pub fn raw_arg(mut self, value: impl Into<OsString>) -> Self {
+        self.args.push((value.into(), RawArg::Raw));
+        self
+    }
+}
+
+impl Debug for RawUnquoted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let style = QuotingStyle::ShellMinimal;
+
+        write!(f, "`")?;
+        for (name, value) in &self.envs {
+            write!(f, "{}={} ", Quoted(name, style), Quoted(value, style))?;
+        }
+
+        let reserved_word = self
+            .program
+            .to_str()
+            .filter(|s| RESERVED_COMMAND_WORDS.binary_search(s).is_ok());
+        if let Some(s) = reserved_word {
+            write!(f, "'{s}'")?;
         } else {
-            write!(f, "{}", Quoted(program))?;
+            write!(f, "{}", Quoted(&self.program, style))?;
         }
 
-        for arg in self.0.get_args() {
-            write!(f, " {}", Quoted(arg))?;
+        for (value, kind) in &self.args {
+            match kind {
+                RawArg::Regular => write!(f, " {}", Quoted(value, style))?,
+                RawArg::Raw => write!(f, " {}", value.to_string_lossy())?,
+            }
         }
         write!(f, "`")
     }
 }
 
-struct Quoted<'a>(&'a OsStr);
+/// Returns whether `c` can be written as-is inside a shell word: not a
+/// C0/C1 control character, and not a zero-width or other invisible
+/// codepoint that would otherwise silently disappear from the rendered
+/// command.
+///
+/// This is a curated list of well-known invisible codepoints rather than a
+/// full Unicode property check (this crate has no dependencies to draw one
+/// from), so it's intentionally minimal and not exhaustive.
+fn is_printable(c: char) -> bool {
+    if c.is_control() {
+        return false;
+    }
+    !matches!(
+        c as u32,
+        0x00ad // soft hyphen
+            | 0x061c // Arabic letter mark
+            | 0x180e // Mongolian vowel separator
+            | 0x200b..=0x200f // zero width space/joiners, left-to-right/right-to-left marks
+            | 0x202a..=0x202e // directional formatting characters
+            | 0x2060..=0x2065 // word joiner and invisible operators
+            | 0xfeff // zero width no-break space / byte order mark
+            | 0xe0000..=0xe007f // invisible tag characters
+    )
+}
+
+/// Writes the body of a bash ANSI-C (`$'...'`) string, without the
+/// surrounding `$'` and `'`.
+fn write_ansi_c_body(f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+    for c in s.chars() {
+        match c {
+            '\n' => write!(f, r"\n")?,
+            '\t' => write!(f, r"\t")?,
+            '\r' => write!(f, r"\r")?,
+            '\\' => write!(f, r"\\")?,
+            '\'' => write!(f, r"\'")?,
+            c if is_printable(c) => write!(f, "{c}")?,
+            c if (c as u32) <= 0x7f => write!(f, r"\x{:02x}", c as u32)?,
+            c if (c as u32) <= 0xffff => write!(f, r"\u{:04x}", c as u32)?,
+            c => write!(f, r"\U{:08x}", c as u32)?,
+        }
+    }
+    Ok(())
+}
+
+/// Writes `s` as a bash ANSI-C (`$'...'`) string.
+fn write_ansi_c(f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+    write!(f, "$'")?;
+    write_ansi_c_body(f, s)?;
+    write!(f, "'")
+}
+
+/// Extension trait for quoting individual strings the same way [`Unquoted`]
+/// quotes a command's program, arguments, and environment variables.
+///
+/// This lets callers quote arbitrary strings for their own diagnostics (e.g.
+/// `format!("failed to read {}", path.quoted())`) without reimplementing
+/// this crate's quoting rules.
+pub trait Quotable {
+    /// Returns a [`Display`] wrapper that renders `self` quoted as a POSIX
+    /// shell would require.
+    fn quoted(&self) -> impl Display + '_;
+}
+
+impl Quotable for OsStr {
+    fn quoted(&self) -> impl Display + '_ {
+        Quoted(self, QuotingStyle::ShellMinimal)
+    }
+}
+
+impl Quotable for str {
+    fn quoted(&self) -> impl Display + '_ {
+        Quoted(OsStr::new(self), QuotingStyle::ShellMinimal)
+    }
+}
+
+impl Quotable for Path {
+    fn quoted(&self) -> impl Display + '_ {
+        Quoted(self.as_os_str(), QuotingStyle::ShellMinimal)
+    }
+}
+
+struct Quoted<'a>(&'a OsStr, QuotingStyle);
 
 impl Display for Quoted<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        if self.0.is_empty() {
+        let Quoted(value, style) = *self;
+
+        if style == QuotingStyle::Literal {
+            return write!(f, "{}", value.to_string_lossy());
+        }
+
+        if style == QuotingStyle::Windows {
+            return fmt_windows_arg(f, &value.to_string_lossy(), false);
+        }
+
+        if value.is_empty() && style != QuotingStyle::ShellEscape {
             return write!(f, "''");
         }
 
-        let s = self.0.to_string_lossy();
-        let has_single_quote = s.contains('\'');
-        let has_special_within_double = s.contains(
+        #[cfg(unix)]
+        {
+            use std::os::unix::ffi::OsStrExt;
+
+            match value.to_str() {
+                Some(s) => fmt_str(f, s, style),
+                // Non-UTF-8 bytes can't be represented as a `char` at all;
+                // render them byte-faithfully instead of lossily replacing
+                // them with U+FFFD.
+                None => fmt_bytes(f, value.as_bytes()),
+            }
+        }
+
+        #[cfg(not(unix))]
+        {
+            fmt_str(f, &value.to_string_lossy(), style)
+        }
+    }
+}
+
+/// Writes `s`, quoting it according to `style` (which must not be
+/// [`QuotingStyle::Literal`]; that style is handled directly by
+/// [`Quoted::fmt`]).
+fn fmt_str(f: &mut fmt::Formatter<'_>, s: &str, style: QuotingStyle) -> fmt::Result {
+    // Control characters and other non-printable codepoints can't be
+    // pasted back into a terminal safely (or at all, legibly); fall back
+    // to bash's ANSI-C quoting, which can represent them unambiguously.
+    if style == QuotingStyle::ShellEscape || s.chars().any(|c| !is_printable(c)) {
+        return write_ansi_c(f, s);
+    }
+
+    let has_single_quote = s.contains('\'');
+    let has_special_within_double = s.contains(
+        [
+            '$', '`', '\\', '"', // POSIX-1.2018
+            '@', // Special within Bash double quotes per docs (unsure why); also extglob
+            '!', // Bash history expansion
+        ]
+        .as_slice(),
+    );
+    let has_special = has_single_quote
+        || has_special_within_double
+        || s.contains(
             [
-                '$', '`', '\\', '"', // POSIX-1.2018
-                '@', // Special within Bash double quotes per docs (unsure why); also extglob
-                '!', // Bash history expansion
+                '|', '&', ';', '<', '>', '(', ')', ' ', '\t', '\n', // POSIX-1.2018
+                '*', '?', '[', '#', '~', '%', // POSIX-1.2018
+                // Technically '=' is in the above list of "may need
+                // to be quoted under certain circumstances" but those
+                // circumstances are generally variable assignments or are
+                // otherwise covered by other characters here.
+                ']', // Bash glob patterns
+                '{', '}', // Bash brace expansion
             ]
             .as_slice(),
         );
-        let has_special = has_single_quote
-            || has_special_within_double
-            || s.contains(
-                [
-                    '|', '&', ';', '<', '>', '(', ')', ' ', '\t', '\n', // POSIX-1.2018
-                    '*', '?', '[', '#', '~', '%', // POSIX-1.2018
-                    // Technically '=' is in the above list of "may need
-                    // to be quoted under certain circumstances" but those
-                    // circumstances are generally variable assignments or are
-                    // otherwise covered by other characters here.
-                    ']', // Bash glob patterns
-                    '{', '}', // Bash brace expansion
-                ]
-                .as_slice(),
-            );
-
-        if has_single_quote && !has_special_within_double {
-            // Use double quotes
-            write!(f, r#""{}""#, s)
-        } else if has_special {
-            // Use single quotes
-            if has_single_quote {
-                write!(f, "'")?;
-                for c in s.chars() {
-                    if c == '\'' {
-                        write!(f, "'\\''")?;
-                    } else {
-                        write!(f, "{}", c)?;
-                    }
+
+    if has_single_quote && !has_special_within_double {
+        // Use double quotes
+        write!(f, r#""{s}""#)
+    } else if has_special || style == QuotingStyle::ShellAlways {
+        // Use single quotes
+        if has_single_quote {
+            write!(f, "'")?;
+            for c in s.chars() {
+                if c == '\'' {
+                    write!(f, "'\\''")?;
+                } else {
+                    write!(f, "{c}")?;
+                }
+            }
+            write!(f, "'")
+        } else {
+            write!(f, "'{s}'")
+        }
+    } else {
+        // Use no quotes
+        write!(f, "{s}")
+    }
+}
+
+/// Writes `bytes`, which are not valid UTF-8, byte-faithfully using bash's
+/// ANSI-C `$'...'` quoting: valid UTF-8 runs are rendered as text (still
+/// subject to the usual control-character escaping), and each invalid byte
+/// is escaped as `\xHH`.
+#[cfg(unix)]
+fn fmt_bytes(f: &mut fmt::Formatter<'_>, mut bytes: &[u8]) -> fmt::Result {
+    write!(f, "$'")?;
+    while !bytes.is_empty() {
+        match std::str::from_utf8(bytes) {
+            Ok(valid) => {
+                write_ansi_c_body(f, valid)?;
+                break;
+            }
+            Err(e) => {
+                let valid_up_to = e.valid_up_to();
+                if valid_up_to > 0 {
+                    // `e.valid_up_to()` guarantees this prefix is valid UTF-8.
+                    write_ansi_c_body(f, std::str::from_utf8(&bytes[..valid_up_to]).unwrap())?;
                 }
-                write!(f, "'")
-            } else {
-                write!(f, "'{}'", s)
+                let invalid_len = e.error_len().unwrap_or(bytes.len() - valid_up_to);
+                for b in &bytes[valid_up_to..valid_up_to + invalid_len] {
+                    write!(f, r"\x{b:02x}")?;
+                }
+                bytes = &bytes[valid_up_to + invalid_len..];
             }
+        }
+    }
+    write!(f, "'")
+}
+
+/// Writes `s` the way `std::process::Command` builds a Windows command
+/// line (reproducing libstd's `make_command_line`/`append_arg`), which is
+/// also how `CommandLineToArgvW` parses one back apart: wrap in `"..."`
+/// when `force_quotes` is set, or `s` is empty or contains a space or tab;
+/// escape embedded `"` as `\"`, and double any run of backslashes that
+/// immediately precedes a quote. `force_quotes` is set for the program
+/// word (`argv0`), which libstd always quotes.
+fn fmt_windows_arg(f: &mut impl fmt::Write, s: &str, force_quotes: bool) -> fmt::Result {
+    let needs_quotes = force_quotes || s.is_empty() || s.contains([' ', '\t']);
+    if needs_quotes {
+        write!(f, "\"")?;
+    }
+
+    let mut backslashes = 0usize;
+    for c in s.chars() {
+        if c == '\\' {
+            backslashes += 1;
         } else {
-            // Use no quotes
-            write!(f, "{}", s)
+            if c == '"' {
+                for _ in 0..=backslashes {
+                    write!(f, "\\")?;
+                }
+            }
+            backslashes = 0;
         }
+        write!(f, "{c}")?;
     }
+
+    if needs_quotes {
+        for _ in 0..backslashes {
+            write!(f, "\\")?;
+        }
+        write!(f, "\"")?;
+    }
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
-    use std::{ffi::OsStr, process::Command};
+    use std::{
+        ffi::{OsStr, OsString},
+        path::Path,
+        process::Command,
+    };
 
-    use crate::{Quoted, Unquoted, RESERVED_COMMAND_WORDS};
+    use crate::{Quotable, Quoted, QuotingStyle, RawUnquoted, Unquoted, RESERVED_COMMAND_WORDS};
 
     #[test]
     fn command_words_sorted() {
@@ -143,7 +533,7 @@ mod tests {
 
     macro_rules! assert_q {
         ($left:expr, $right:expr) => {
-            assert_eq!(Quoted(OsStr::new($left)).to_string(), $right)
+            assert_eq!(Quoted(OsStr::new($left), QuotingStyle::ShellMinimal).to_string(), $right)
         };
     }
 
@@ -165,8 +555,6 @@ mod tests {
         assert_q!(r"\meow", r"'\meow'");
         assert_q!(r#""meow""#, r#"'"meow"'"#);
         assert_q!("meow meow", "'meow meow'");
-        assert_q!("meow\tmeow", "'meow\tmeow'");
-        assert_q!("meow\nmeow", "'meow\nmeow'");
         assert_q!("meow*", "'meow*'");
         assert_q!("meow?", "'meow?'");
         assert_q!("[meow", "'[meow'");
@@ -191,8 +579,6 @@ mod tests {
         assert_q!("(meow's", r#""(meow's""#);
         assert_q!("meow's)", r#""meow's)""#);
         assert_q!("meow's meow", r#""meow's meow""#);
-        assert_q!("meow's\tmeow", "\"meow's\tmeow\"");
-        assert_q!("meow's\nmeow", "\"meow's\nmeow\"");
         assert_q!("meow's*", r#""meow's*""#);
         assert_q!("meow's?", r#""meow's?""#);
         assert_q!("[meow's", r#""[meow's""#);
@@ -212,6 +598,57 @@ mod tests {
         assert_q!("!meow's", r"'!meow'\''s'");
     }
 
+    #[test]
+    fn quoted_ansi_c() {
+        // control characters: use bash's ANSI-C `$'...'` quoting
+        assert_q!("meow\tmeow", r"$'meow\tmeow'");
+        assert_q!("meow\nmeow", r"$'meow\nmeow'");
+        assert_q!("meow\rmeow", r"$'meow\rmeow'");
+        assert_q!("meow\x01meow", r"$'meow\x01meow'");
+        assert_q!("meow\x7fmeow", r"$'meow\x7fmeow'");
+
+        // a single quote or backslash alongside a control character still
+        // needs escaping within the ANSI-C string
+        assert_q!("meow's\tmeow", r"$'meow\'s\tmeow'");
+        assert_q!("meow's\nmeow", r"$'meow\'s\nmeow'");
+        assert_q!("meow\\\tmeow", r"$'meow\\\tmeow'");
+
+        // zero-width codepoints are treated as non-printable too
+        assert_q!("meow\u{200b}meow", r"$'meow\u200bmeow'");
+
+        // codepoints outside the basic multilingual plane use `\U########`,
+        // but only when they're actually non-printable; plain emoji like
+        // U+1F600 render literally
+        assert_q!("meow\u{e0001}meow", r"$'meow\U000e0001meow'");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn quoted_non_utf8() {
+        use std::os::unix::ffi::OsStrExt;
+
+        macro_rules! assert_q_bytes {
+            ($left:expr, $right:expr) => {
+                assert_eq!(Quoted(OsStr::from_bytes($left), QuotingStyle::ShellMinimal).to_string(), $right)
+            };
+        }
+
+        // a lone invalid byte
+        assert_q_bytes!(b"\xff", r"$'\xff'");
+        // invalid bytes surrounded by valid UTF-8
+        assert_q_bytes!(b"meow\xffmeow", r"$'meow\xffmeow'");
+        // an invalid byte alongside a character that would already need
+        // ANSI-C escaping
+        assert_q_bytes!(b"meow\xff\tmeow", r"$'meow\xff\tmeow'");
+    }
+
+    #[test]
+    fn quotable() {
+        assert_eq!(OsStr::new("meow meow").quoted().to_string(), "'meow meow'");
+        assert_eq!("meow meow".quoted().to_string(), "'meow meow'");
+        assert_eq!(Path::new("meow meow").quoted().to_string(), "'meow meow'");
+    }
+
     macro_rules! assert_u {
         ($value:expr, $display:expr) => {
             assert_eq!(format!("{:?}", Unquoted(&$value)), $display)
@@ -243,4 +680,300 @@ mod tests {
             r#"`BLAH1=blah BLAH2='"blah'\''s blah"' BLAH3='\"blah'\''s blah\"' program`"#
         );
     }
+
+    macro_rules! assert_styled {
+        ($value:expr, $style:expr, $display:expr) => {
+            assert_eq!(
+                format!("{:?}", Unquoted::with_style(&$value, $style)),
+                $display
+            )
+        };
+    }
+
+    #[test]
+    fn quoting_style() {
+        let mut cmd = Command::new("case");
+        cmd.args(["arg1", "arg b", "arg'c"]);
+
+        assert_styled!(cmd, QuotingStyle::Literal, "`case arg1 arg b arg'c`");
+        assert_styled!(
+            cmd,
+            QuotingStyle::ShellMinimal,
+            r#"`'case' arg1 'arg b' "arg'c"`"#
+        );
+        assert_styled!(
+            cmd,
+            QuotingStyle::ShellAlways,
+            r#"`'case' 'arg1' 'arg b' "arg'c"`"#
+        );
+        assert_styled!(
+            cmd,
+            QuotingStyle::ShellEscape,
+            r"`$'case' $'arg1' $'arg b' $'arg\'c'`"
+        );
+    }
+
+    macro_rules! assert_windows {
+        ($left:expr, $right:expr) => {
+            assert_eq!(
+                Quoted(OsStr::new($left), QuotingStyle::Windows).to_string(),
+                $right
+            )
+        };
+    }
+
+    #[test]
+    fn quoting_style_windows() {
+        assert_windows!("", "\"\"");
+        assert_windows!("meow", "meow");
+        assert_windows!("meow meow", "\"meow meow\"");
+        assert_windows!("meow\tmeow", "\"meow\tmeow\"");
+        // an embedded quote with no preceding backslashes: escape with one
+        assert_windows!("say\"hi", r#"say\"hi"#);
+        // an embedded quote preceded by a backslash: double the backslashes
+        // and add one more before the quote
+        assert_windows!(r#"a\"b"#, r#"a\\\"b"#);
+        // a trailing run of backslashes before the closing quote is doubled
+        assert_windows!(r"C:\Program Files\", r#""C:\Program Files\\""#);
+    }
+
+    #[test]
+    fn quoting_style_windows_program() {
+        // libstd's `make_command_line` always force-quotes `argv0`, even
+        // when it has no spaces or tabs, so `Unquoted` must reproduce that
+        // here even though `Quoted` alone wouldn't add quotes.
+        let cmd = Command::new("program");
+        assert_styled!(cmd, QuotingStyle::Windows, r#"`"program"`"#);
+
+        let mut cmd = Command::new("C:\\Program Files\\app.exe");
+        cmd.arg("meow");
+        assert_styled!(
+            cmd,
+            QuotingStyle::Windows,
+            r#"`"C:\Program Files\app.exe" meow`"#
+        );
+    }
+
+    #[test]
+    fn raw_unquoted() {
+        assert_eq!(
+            format!("{:?}", RawUnquoted::new("cmd.exe").arg("/c").raw_arg("dir *.rs")),
+            "`cmd.exe /c dir *.rs`"
+        );
+
+        // a raw argument is never quoted, even if it contains characters
+        // that would otherwise force quoting
+        assert_eq!(
+            format!("{:?}", RawUnquoted::new("program").raw_arg("arg 'one'")),
+            "`program arg 'one'`"
+        );
+
+        // a regular argument added alongside a raw one is still quoted the
+        // usual way
+        assert_eq!(
+            format!(
+                "{:?}",
+                RawUnquoted::new("program")
+                    .raw_arg("raw arg")
+                    .arg("regular arg")
+            ),
+            "`program raw arg 'regular arg'`"
+        );
+
+        assert_eq!(
+            format!("{:?}", RawUnquoted::new("case").arg("meow")),
+            "`'case' meow`"
+        );
+    }
+
+    /// A tiny POSIX shell-word tokenizer, plus support for bash's `$'...'`
+    /// ANSI-C quoting (since [`Unquoted::to_command_string`] can emit it).
+    /// Exists only to back the round-trip tests below, so it doesn't
+    /// become a runtime dependency of the crate.
+    fn shell_split(s: &str) -> Vec<String> {
+        #[derive(PartialEq)]
+        enum State {
+            Delimiter,
+            Unquoted,
+            UnquotedBackslash,
+            SingleQuoted,
+            DoubleQuoted,
+            DoubleQuotedBackslash,
+            AnsiC,
+        }
+
+        let mut words = Vec::new();
+        let mut word = String::new();
+        let mut state = State::Delimiter;
+        let mut chars = s.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            state = match state {
+                State::Delimiter | State::Unquoted => match c {
+                    ' ' | '\t' | '\n' if state == State::Unquoted => {
+                        words.push(std::mem::take(&mut word));
+                        State::Delimiter
+                    }
+                    ' ' | '\t' | '\n' => State::Delimiter,
+                    '\'' => State::SingleQuoted,
+                    '"' => State::DoubleQuoted,
+                    '$' if chars.peek() == Some(&'\'') => {
+                        chars.next();
+                        State::AnsiC
+                    }
+                    '\\' => State::UnquotedBackslash,
+                    c => {
+                        word.push(c);
+                        State::Unquoted
+                    }
+                },
+                State::UnquotedBackslash => {
+                    word.push(c);
+                    State::Unquoted
+                }
+                State::SingleQuoted => {
+                    if c == '\'' {
+                        State::Unquoted
+                    } else {
+                        word.push(c);
+                        State::SingleQuoted
+                    }
+                }
+                State::DoubleQuoted => match c {
+                    '"' => State::Unquoted,
+                    '\\' => State::DoubleQuotedBackslash,
+                    c => {
+                        word.push(c);
+                        State::DoubleQuoted
+                    }
+                },
+                State::DoubleQuotedBackslash => {
+                    // Inside double quotes, a backslash only keeps its
+                    // special meaning before `$`, `` ` ``, `"`, `\`, or a
+                    // newline; otherwise it's literal.
+                    if !matches!(c, '$' | '`' | '"' | '\\' | '\n') {
+                        word.push('\\');
+                    }
+                    if c != '\n' {
+                        word.push(c);
+                    }
+                    State::DoubleQuoted
+                }
+                State::AnsiC if c == '\'' => State::Unquoted,
+                State::AnsiC if c == '\\' => {
+                    match chars.next().expect("unterminated $'...' escape") {
+                        'n' => word.push('\n'),
+                        't' => word.push('\t'),
+                        'r' => word.push('\r'),
+                        '\\' => word.push('\\'),
+                        '\'' => word.push('\''),
+                        'x' => {
+                            let hex: String = chars.by_ref().take(2).collect();
+                            word.push(u8::from_str_radix(&hex, 16).unwrap() as char);
+                        }
+                        'u' => {
+                            let hex: String = chars.by_ref().take(4).collect();
+                            word.push(char::from_u32(u32::from_str_radix(&hex, 16).unwrap()).unwrap());
+                        }
+                        'U' => {
+                            let hex: String = chars.by_ref().take(8).collect();
+                            word.push(char::from_u32(u32::from_str_radix(&hex, 16).unwrap()).unwrap());
+                        }
+                        other => word.push(other),
+                    }
+                    State::AnsiC
+                }
+                State::AnsiC => {
+                    word.push(c);
+                    State::AnsiC
+                }
+            };
+        }
+
+        if state != State::Delimiter {
+            words.push(word);
+        }
+
+        words
+    }
+
+    /// Asserts that rendering `cmd` with [`Unquoted::to_command_string`]
+    /// and tokenizing the result with [`shell_split`] reproduces
+    /// `program` and `args` exactly.
+    fn assert_round_trips(program: &str, args: &[&str]) {
+        let mut cmd = Command::new(program);
+        cmd.args(args);
+        let rendered = Unquoted(&cmd).to_command_string();
+        let tokens = shell_split(&rendered);
+        let expected: Vec<String> = std::iter::once(program)
+            .chain(args.iter().copied())
+            .map(String::from)
+            .collect();
+        assert_eq!(tokens, expected, "rendered as: {rendered}");
+    }
+
+    #[test]
+    fn round_trip() {
+        assert_round_trips("program", &[]);
+        assert_round_trips("program", &["arg1", "arg2"]);
+        assert_round_trips("case", &["arg"]);
+
+        // characters that force single- or double-quoting
+        assert_round_trips(
+            "program",
+            &[
+                "meow meow",
+                "meow's",
+                "$meow's",
+                "meow;meow",
+                "meow|meow",
+                "meow&meow",
+                "",
+                "'",
+                "\"",
+                "meow\"meow",
+            ],
+        );
+
+        // control characters and other non-printable codepoints, which
+        // force bash's ANSI-C `$'...'` quoting
+        assert_round_trips(
+            "program",
+            &[
+                "meow\tmeow",
+                "meow\nmeow",
+                "meow\rmeow",
+                "meow\x01meow",
+                "meow's\tmeow",
+                "meow\u{200b}meow",
+                "meow\u{e0001}meow",
+            ],
+        );
+
+        // a mix of everything in a single command
+        assert_round_trips(
+            "case",
+            &[
+                "plain",
+                "needs single quotes!",
+                "needs's double quotes",
+                "has\ta control char",
+                "",
+            ],
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn round_trip_non_utf8() {
+        use std::os::unix::ffi::OsStringExt;
+
+        let mut cmd = Command::new("program");
+        cmd.arg(OsString::from_vec(b"meow\xffmeow".to_vec()));
+        let rendered = Unquoted(&cmd).to_command_string();
+        // the byte `\xff` comes back from `shell_split` as the character
+        // U+00FF, since the tokenizer returns `String`s and decodes each
+        // `\xHH` escape to the `char` with that code point
+        assert_eq!(shell_split(&rendered), ["program", "meow\u{ff}meow"]);
+    }
 }